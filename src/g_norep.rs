@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::ptr;
+use std::sync::atomic::AtomicUsize;
 use std::sync::Mutex;
 
 // estructura de la lista
@@ -6,57 +8,130 @@ pub(crate) struct GListNodo {
 	pub(crate) begin: *mut GStrInterner,
 	pub(crate) end: *mut GStrInterner
 }
-// lista
-pub(crate) static mut GLIST_NODO : GListNodo = GListNodo{
-	begin: ptr::null_mut(),
-	end: ptr::null_mut()
-};
-// mutex
-pub(crate) static mut GNOREP_LOOCK : Mutex<()> = Mutex::new(());
+
+// estado de un interner: su lista enlazada, su indice por hash y el mutex
+// que protege ambos. Vive en el heap para que su direccion sea estable aunque
+// el `Interner` que lo posee se mueva, ya que cada nodo guarda un puntero
+// de vuelta (`owner`) hacia este estado.
+pub(crate) struct GInternerState {
+	pub(crate) lista: GListNodo,
+	pub(crate) indice: HashMap<(u64,u64), Vec<*mut GStrInterner>>,
+	pub(crate) lock: Mutex<()>
+}
+
+impl GInternerState {
+	pub(crate) fn new() -> Self {
+		GInternerState {
+			lista: GListNodo {
+				begin: ptr::null_mut(),
+				end: ptr::null_mut()
+			},
+			indice: HashMap::new(),
+			lock: Mutex::new(())
+		}
+	}
+}
 
 pub(crate) struct GStrInterner {
 	pub(crate) value: String,
 	pub(crate) len: usize,
-	pub(crate) count: usize,
-	pub(crate) hash: u32,
+	// atomico porque `Clone`/`Drop` de `GStr` pueden tocarlo desde hilos
+	// distintos sin pasar por el mutex del interner (solo las mutaciones de
+	// la lista/el indice lo necesitan)
+	pub(crate) count: AtomicUsize,
+	// huella de 128 bits (dos mitades de 64 bits), usada junto con `len`
+	// como clave autoritativa del bucket sin necesidad de comparar bytes
+	pub(crate) hash_alto: u64,
+	pub(crate) hash_bajo: u64,
 	pub(crate) next: *mut GStrInterner,
-	pub(crate) prev: *mut GStrInterner
+	pub(crate) prev: *mut GStrInterner,
+	// interner dueño de este nodo, para que `remove` edite la lista y el
+	// indice correctos sin depender de una tabla global
+	pub(crate) owner: *mut GInternerState
+}
+
+/// Reserva un `GStrInterner` sin pasar por la asignación infalible de
+/// `Box::new`: usa `std::alloc` directamente y devuelve `FalloAsignacion` en
+/// vez de abortar si el allocator no puede satisfacer la reserva.
+pub(crate) fn try_alloc_nodo(gstr: GStrInterner) -> Result<*mut GStrInterner, crate::GStrError> {
+	use std::alloc::{alloc, Layout};
+	unsafe {
+		let layout = Layout::new::<GStrInterner>();
+		let pun_str = alloc(layout) as *mut GStrInterner;
+		if pun_str.is_null() {
+			return Err(crate::GStrError::FalloAsignacion);
+		}
+		pun_str.write(gstr);
+		Ok(pun_str)
+	}
 }
 
-const CHAR_COUSIN : u32= 486187739;
-const CHAR_S : u32= 31;
-pub(crate) fn ohash(c: &str, mlen: &mut usize) -> u32 {
-	let mut hash : u32 = 0;
+// multiplicadores al estilo FxHash: impares, bien mezclados bit a bit, y
+// distintos entre si para que las dos mitades no queden correlacionadas
+const K_ALTO : u64 = 0x517c_c1b7_2722_0a95;
+const K_BAJO : u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Calcula una huella de 128 bits (como dos mitades de 64 bits) de `c`,
+/// junto con su longitud en caracteres. Con 128 bits la probabilidad de
+/// colision es despreciable, asi que `GStrInterner::compare` puede confiar
+/// en la huella mas `len` sin tener que recorrer la cadena byte a byte.
+pub(crate) fn fingerprint(c: &str, mlen: &mut usize) -> (u64, u64) {
+	let mut alto : u64 = 0;
+	let mut bajo : u64 = 0;
 	let mut len : usize = 0;
 	let mut iter = c.chars();
 	while let Some(ch) = iter.next() {
 		len+=1;
-		hash = hash.wrapping_mul(CHAR_S).wrapping_add(ch as u32).wrapping_mul(len as u32);
-	} 
+		let byte = ch as u64;
+		alto = (alto.rotate_left(5) ^ byte).wrapping_mul(K_ALTO);
+		bajo = (bajo.rotate_left(5) ^ byte.wrapping_add(1)).wrapping_mul(K_BAJO);
+	}
 	(*mlen) = len;
-	hash % CHAR_COUSIN
+	(alto, bajo)
 }
 impl GStrInterner {
 	pub(crate) fn remove(&mut self) {
-		if self.count == 0{
+		if self.count.load(std::sync::atomic::Ordering::Acquire) == 0{
 			unsafe {
-				let _unused = GNOREP_LOOCK.lock().expect("No se pudo bloquear el mutex");
+				let estado = &mut *self.owner;
+				// un destructor no debe hacer panic: si el mutex esta
+				// envenenado seguimos igual con los datos que protegia
+				let _unused = estado.lock.lock().unwrap_or_else(|venenado| venenado.into_inner());
 				if self.next != ptr::null_mut() {
 					(*self.next).prev = self.prev;
 				}else{
-					GLIST_NODO.end = self.prev;
+					estado.lista.end = self.prev;
 				}
 				if self.prev != ptr::null_mut(){
 					(*self.prev).next = self.next;
 				}else{
-					GLIST_NODO.begin = self.next;
+					estado.lista.begin = self.next;
+				}
+				// quitar el puntero del bucket del indice de hash
+				let self_ptr = self as *mut GStrInterner;
+				let clave = (self.hash_alto, self.hash_bajo);
+				let mut vacio = false;
+				if let Some(bucket) = estado.indice.get_mut(&clave) {
+					bucket.retain(|&p| p != self_ptr);
+					vacio = bucket.is_empty();
+				}
+				if vacio {
+					estado.indice.remove(&clave);
 				}
 			}
 		}
 	}
-	pub(crate) fn compare(&self, hash: u32, len: usize, strn: &str) -> bool {
-		self.hash == hash && 
-		self.len == len && 
-		self.value == strn
+	/// La huella de 128 bits mas `len` son autoritativas: con una colision
+	/// de probabilidad despreciable, no hace falta comparar bytes en release.
+	/// En depuracion se verifica igual la cadena completa, para detectar de
+	/// inmediato cualquier bug en el mezclado de la huella.
+	pub(crate) fn compare(&self, hash_alto: u64, hash_bajo: u64, len: usize, strn: &str) -> bool {
+		let coincide = self.hash_alto == hash_alto &&
+			self.hash_bajo == hash_bajo &&
+			self.len == len;
+		if coincide {
+			debug_assert!(self.value == strn, "colision de huella de 128 bits (deberia ser practicamente imposible)");
+		}
+		coincide
 	}
-}
\ No newline at end of file
+}
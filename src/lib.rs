@@ -27,12 +27,13 @@
 //! `GStr` utiliza un **contador de referencias** para gestionar la vida útil de las cadenas (Similar a lo que hace la estructura Rc). Esto significa que cada vez que una nueva variable hace referencia a una cadena, el contador de referencias aumenta. Cuando una variable deja de usar la cadena, el contador disminuye.
 mod g_norep;
 use crate::g_norep::*;
+#[cfg(feature = "serde")]
+mod serde_impls;
 use std::ops::Deref;
 use std::ptr;
 use std::fmt::{
     Display,Formatter
 };
-use std::thread;
 
 /// La trait `StringInfo` es esencial para trabajar con GStr. 
 /// Su propósito principal es manejar valores de manera 
@@ -85,73 +86,231 @@ pub struct GStr {
     value: *mut GStrInterner
 }
 
+// `Clone`/`Drop` solo tocan el nodo a traves del contador atomico, y las
+// mutaciones de la lista/el indice del interner dueño estan protegidas por
+// su mutex (igual que en `Interner`, ver `unsafe impl Send/Sync for
+// Interner` mas abajo), asi que un `GStr` puede moverse y compartirse
+// entre hilos sin problema.
+unsafe impl Send for GStr {}
+unsafe impl Sync for GStr {}
+
+// comprobacion en tiempo de compilacion de que lo anterior no se regresiona
+// por accidente (p. ej. si se le agrega un campo no-Send/Sync a `GStr`)
+#[allow(dead_code)]
+fn _assert_gstr_send_sync() {
+    fn need<T: Send + Sync>() {}
+    need::<GStr>();
+}
+
 impl GStr {
-    fn search_value(strn: &str, len: usize, hash: u32, mut value: *mut GStrInterner) -> Option<GStr>{
-        // recorrer la lista
+    /// Esta función realiza una búsqueda en las cadenas previamente creadas para encontrar una que coincida con la cadena recibida.
+    /// - **Si la cadena existe**, la función te devuelve un `GStr` que apunta a esa cadena existente.
+    /// - **Si la cadena no existe**, la función crea una nueva cadena y te devuelve un `GStr` que apunta a esta nueva cadena.
+    ///
+    /// Internamente se interna sobre una `Interner` global por defecto,
+    /// compartida por todo el proceso. Para aislar el internado (por hilo,
+    /// por petición, etc.) usa directamente [`Interner::intern`].
+    ///
+    /// Esta función hace panic si el mutex del interner está envenenado o si
+    /// no se pudo reservar memoria; para un contexto que no puede permitirse
+    /// un panic, usa [`GStr::try_new`].
+    pub fn new<T: StringInfo>(strn: T) -> GStr{
+        GStr::try_new(strn).expect("no se pudo internar la cadena")
+    }
+    /// Igual que [`GStr::new`], pero sin hacer panic: devuelve un
+    /// `GStrError` si el mutex del interner global está envenenado o si la
+    /// asignación de memoria para el nuevo nodo falla.
+    pub fn try_new<T: StringInfo>(strn: T) -> Result<GStr, GStrError> {
+        global_interner().try_intern(strn)
+    }
+    /// Esta función devuelve la cantidad de caracteres que contiene la cadena.
+    pub fn chars_count(&self) -> usize {
+        unsafe { (*self.value).len }
+    }
+}
+
+/// Errores que puede producir el internado sin recurrir a un panic. Pensado
+/// para contextos que no pueden permitirse una asignación o un bloqueo
+/// infalibles (por ejemplo, código embebido o estilo kernel).
+#[derive(Debug)]
+pub enum GStrError {
+    /// El mutex del interner estaba envenenado: otro hilo hizo panic mientras lo tenía bloqueado.
+    LockEnvenenado,
+    /// El allocator no pudo reservar memoria para el nuevo nodo interno.
+    FalloAsignacion,
+}
+
+impl Display for GStrError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            GStrError::LockEnvenenado => write!(f, "el mutex del interner estaba envenenado"),
+            GStrError::FalloAsignacion => write!(f, "no se pudo asignar memoria para el nodo interno"),
+        }
+    }
+}
+
+impl std::error::Error for GStrError {}
+
+/// Tabla de internado aislada: agrupa cadenas únicas bajo su propia lista,
+/// su propio indice por hash y su propio mutex, sin compartir estado con
+/// ninguna otra `Interner` ni con la tabla global que usa [`GStr::new`].
+///
+/// Esto permite que una libreria monte su propia arena de internado (por
+/// ejemplo, una por hilo o por petición) sin contender por el mutex
+/// proceso-ancho ni mezclar sus cadenas con las de otro consumidor.
+pub struct Interner {
+    estado: *mut GInternerState
+}
+
+// El acceso concurrente a `estado` esta protegido por el mutex que vive
+// dentro de `GInternerState`, asi que es seguro compartir y mover `Interner`
+// entre hilos.
+unsafe impl Send for Interner {}
+unsafe impl Sync for Interner {}
+
+impl Drop for Interner {
+    /// Libera todos los nodos que queden en la tabla, y la tabla misma.
+    ///
+    /// `GStr` no lleva un lifetime atado a su `Interner` (es un puntero
+    /// crudo), así que nada impide en código completamente seguro que un
+    /// `GStr` sobreviva al `Interner` del que salió. Si eso ocurre, liberar
+    /// los nodos igualmente dejaría a ese `GStr` apuntando a memoria ya
+    /// liberada (use-after-free, y luego un doble `free` cuando ese `GStr`
+    /// se dropee). Por eso primero se comprueba si queda algún nodo con
+    /// referencias vivas (`count != 0`): si lo hay, se prefiere perder la
+    /// arena entera (nodos y estado quedan sin liberar) antes que arriesgar
+    /// un UAF. Solo cuando ningún nodo tiene referencias vivas se libera
+    /// todo, que es el caso normal.
+    fn drop(&mut self) {
         unsafe {
-            while value != ptr::null_mut() {
-                if (*value).compare(hash,len, strn) {
+            let mut nodo = (*self.estado).lista.begin;
+            while nodo != ptr::null_mut() {
+                if (*nodo).count.load(std::sync::atomic::Ordering::Acquire) != 0 {
+                    // queda al menos un `GStr` vivo apuntando a esta arena:
+                    // se deja toda la arena sin liberar en vez de arriesgar
+                    // un use-after-free/doble-free.
+                    return;
+                }
+                nodo = (*nodo).next;
+            }
 
-                    (*value).count+=1;
-                    return Some(GStr {
-                        value: value
-                    });
+            let mut nodo = (*self.estado).lista.begin;
+            while nodo != ptr::null_mut() {
+                let siguiente = (*nodo).next;
+                drop(Box::from_raw(nodo));
+                nodo = siguiente;
+            }
+            drop(Box::from_raw(self.estado));
+        }
+    }
+}
 
+impl Interner {
+    /// Crea una tabla de internado nueva y vacía, independiente de cualquier otra.
+    pub fn new() -> Self {
+        Interner {
+            estado: Box::into_raw(Box::new(GInternerState::new()))
+        }
+    }
+    fn search_value(estado: &GInternerState, strn: &str, len: usize, hash_alto: u64, hash_bajo: u64) -> Option<GStr>{
+        // solo se recorren los nodos que comparten el mismo bucket de huella
+        unsafe {
+            if let Some(bucket) = estado.indice.get(&(hash_alto, hash_bajo)) {
+                for &value in bucket.iter() {
+                    if (*value).compare(hash_alto, hash_bajo, len, strn) {
+                        // un nodo con contador 0 ya lo esta liberando el `Drop`
+                        // de otro hilo: no se revive, se crea uno nuevo
+                        let revivido = (*value).count.fetch_update(
+                            std::sync::atomic::Ordering::AcqRel,
+                            std::sync::atomic::Ordering::Acquire,
+                            |c| if c == 0 { None } else { Some(c + 1) }
+                        );
+                        if revivido.is_err() {
+                            continue;
+                        }
+                        return Some(GStr {
+                            value: value
+                        });
+                    }
                 }
-                value = (*value).next;
             }
         }
         None
     }
-    fn create_gstr(vstr: String, len: usize, hash: u32) -> GStr {
-        unsafe {    
+    // Recibe el `&GInternerState` que `try_intern` ya tiene prestado (el
+    // mismo que sostiene el guard del mutex) en vez de volver a pedirlo
+    // prestado como `&mut *self.estado`: crear una referencia mutable sobre
+    // toda la estructura mientras ese prestamo compartido sigue vivo (y se
+    // usa de nuevo al soltar el guard al volver de esta función) es un alias
+    // invalido segun las reglas de stacked borrows. En su lugar, las
+    // escrituras se hacen a traves de un puntero crudo obtenido del mismo
+    // `estado` ya prestado, igual que `search_value` muta nodos sin
+    // materializar un `&mut` sobre el objeto entero.
+    fn try_create_gstr(estado: &GInternerState, vstr: String, len: usize, hash_alto: u64, hash_bajo: u64) -> Result<GStr, GStrError> {
+        unsafe {
+            let estado_ptr = estado as *const GInternerState as *mut GInternerState;
             let gstr = GStrInterner {
                 value: vstr,
-                len,hash,
-                count: 1,
+                len,
+                hash_alto,
+                hash_bajo,
+                count: std::sync::atomic::AtomicUsize::new(1),
                 next: ptr::null_mut(),
-                prev: GLIST_NODO.end
+                prev: estado.lista.end,
+                owner: estado_ptr
             };
-            let pun_str = Box::into_raw(Box::new(gstr));
-        
+            let pun_str = try_alloc_nodo(gstr)?;
+
             // si hay un elemento anterior, actualizar su valor next
-            if GLIST_NODO.end != ptr::null_mut(){
-                (*GLIST_NODO.end).next = pun_str;
+            if estado.lista.end != ptr::null_mut(){
+                (*estado.lista.end).next = pun_str;
             }
-            GLIST_NODO.end = pun_str;
+            (*estado_ptr).lista.end = pun_str;
             // se debe colocar un valor a begin si no tiene
-            if GLIST_NODO.begin == ptr::null_mut(){
-                GLIST_NODO.begin = GLIST_NODO.end;
-            }
-            GStr {
-                value: GLIST_NODO.end
+            if (*estado_ptr).lista.begin == ptr::null_mut(){
+                (*estado_ptr).lista.begin = (*estado_ptr).lista.end;
             }
+            // registrar el puntero en el bucket del indice de huella
+            (*estado_ptr).indice.entry((hash_alto, hash_bajo)).or_insert_with(Vec::new).push(pun_str);
+            Ok(GStr {
+                value: (*estado_ptr).lista.end
+            })
         }
     }
-    /// Esta función realiza una búsqueda en las cadenas previamente creadas para encontrar una que coincida con la cadena recibida.
-    /// - **Si la cadena existe**, la función te devuelve un `GStr` que apunta a esa cadena existente.
-    /// - **Si la cadena no existe**, la función crea una nueva cadena y te devuelve un `GStr` que apunta a esta nueva cadena.
-    pub fn new<T: StringInfo>(strn: T) -> GStr{
+    /// Interna `strn` en esta tabla: si ya existe una cadena igual, devuelve
+    /// un `GStr` que la referencia; si no, crea una entrada nueva.
+    ///
+    /// Hace panic si el mutex está envenenado o si no se pudo reservar
+    /// memoria; usa [`Interner::try_intern`] para evitarlo.
+    pub fn intern<T: StringInfo>(&self, strn: T) -> GStr {
+        self.try_intern(strn).expect("no se pudo internar la cadena")
+    }
+    /// Igual que [`Interner::intern`], pero sin hacer panic.
+    pub fn try_intern<T: StringInfo>(&self, strn: T) -> Result<GStr, GStrError> {
         let mut len : usize = 0;
-        let hash : u32 = ohash(strn.get_str_ref(),&mut len);
+        let (hash_alto, hash_bajo) = fingerprint(strn.get_str_ref(),&mut len);
         unsafe {
+            let estado = &*self.estado;
             // bloquear mutex
-            let _unused = GNOREP_LOOCK.lock().expect("No se pudo bloquear el mutex");
-            let value : *mut GStrInterner = GLIST_NODO.begin;
+            let _unused = estado.lock.lock().map_err(|_| GStrError::LockEnvenenado)?;
 
-            match GStr::search_value(strn.get_str_ref(), len, hash, value) {
-                Some(v) => return v,
+            match Interner::search_value(estado, strn.get_str_ref(), len, hash_alto, hash_bajo) {
+                Some(v) => return Ok(v),
                 _=> {}
-            } 
+            }
 
-           
-            GStr::create_gstr(strn.get_str(), len, hash)
+            Interner::try_create_gstr(estado, strn.get_str(), len, hash_alto, hash_bajo)
         }
     }
-    /// Esta función devuelve la cantidad de caracteres que contiene la cadena.
-    pub fn chars_count(&self) -> usize {
-        unsafe { (*self.value).len } 
-    }
+}
+
+// tabla de internado global que usa `GStr::new`, inicializada de forma
+// perezosa en el primer uso. `OnceLock` evita el hazard de alias de un
+// `static mut` (y el consiguiente warning de `static_mut_refs`).
+static GINTERNER_GLOBAL : std::sync::OnceLock<Interner> = std::sync::OnceLock::new();
+
+fn global_interner() -> &'static Interner {
+    GINTERNER_GLOBAL.get_or_init(Interner::new)
 }
 impl AsRef<str> for GStr {
     /// Esta función retorna una referencia inmutable a la cadena.
@@ -161,21 +320,28 @@ impl AsRef<str> for GStr {
 }
 impl Clone for GStr {
     /// Clona el `GStr`. No crea una copia de la cadena, simplemente crea un nuevo `GStr` que apunta a la **cadena existente**.
-    fn clone(&self) -> Self{ 
-        unsafe {(*self.value).count+= 1;}
+    ///
+    /// El contador se incrementa de forma atómica, así que clonar el mismo
+    /// `GStr` desde varios hilos a la vez no pierde incrementos.
+    fn clone(&self) -> Self{
+        unsafe {(*self.value).count.fetch_add(1, std::sync::atomic::Ordering::AcqRel);}
         GStr {
             value: self.value
         }
     }
-}   
+}
 
 impl Drop for GStr {
     /// funcion para determinar si eliminar la cadena.
+    ///
+    /// El decremento es atómico: `fetch_sub` devuelve el valor previo, así
+    /// que solo el hilo que lo lleva de 1 a 0 libera el nodo, sin importar
+    /// cuántos `Clone`/`Drop` de copias de este mismo `GStr` corran en
+    /// paralelo en otros hilos.
     fn drop(&mut self) {
         let value = self.value;
         unsafe {
-            (*value).count-= 1;
-            if (*value).count == 0 {
+            if (*value).count.fetch_sub(1, std::sync::atomic::Ordering::AcqRel) == 1 {
                 (*value).remove();
                 let _ = Box::from_raw(value); // dejar que rust elimine el valor
             }
@@ -208,6 +374,49 @@ impl PartialEq for GStr {
     }
 }
 
+/// El internado garantiza un único puntero canónico por cadena única, así
+/// que dos `GStr` creados a partir de cadenas iguales en el mismo `Interner`
+/// comparten el mismo nodo y por lo tanto son iguales con `PartialEq`.
+impl Eq for GStr {}
+
+impl std::hash::Hash for GStr {
+    /// Se hashea el puntero interno (`self.value as usize`) en vez de los
+    /// bytes de la cadena, así que el hash es O(1) sin importar cuán larga
+    /// sea la cadena. Esto es válido porque dos `GStr` de cadenas iguales
+    /// internadas en el mismo `Interner` comparten el mismo nodo, luego el
+    /// mismo puntero.
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (self.value as usize).hash(state);
+    }
+}
+
+impl PartialOrd for GStr {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for GStr {
+    /// Compara primero por contenido (orden lexicográfico, el caso que
+    /// importa para `BTreeSet<GStr>`/`.sort()`), y solo desempata por
+    /// identidad del nodo interno cuando el contenido es igual.
+    ///
+    /// El desempate por puntero es necesario porque dos `GStr` de cadenas
+    /// iguales creadas en `Interner`s distintos son nodos distintos: si
+    /// `cmp` devolviera `Ordering::Equal` para ellos harían `!=` vía `Eq`
+    /// pero `Equal` vía `Ord`, violando el contrato de `Ord`/`Eq`/`Hash` y
+    /// haciendo que un `BTreeSet<GStr>` colapsara silenciosamente esas dos
+    /// cadenas en una sola entrada mientras un `HashSet<GStr>` las mantenía
+    /// separadas. En el caso habitual (mismo `Interner`, o interners
+    /// distintos pero contenido distinto) el desempate nunca se alcanza y
+    /// el orden es puramente lexicográfico.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_ref()
+            .cmp(other.as_ref())
+            .then_with(|| (self.value as usize).cmp(&(other.value as usize)))
+    }
+}
+
 #[doc(hidden)]
 impl Deref for GStr {
     type Target = str;
@@ -224,6 +433,7 @@ impl Deref for GStr {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::thread;
 
     #[test]
     fn it_works() {
@@ -266,6 +476,141 @@ mod tests {
 
         handle.join().unwrap();
         temp.clear();
-       
+
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip_reinterna_y_dedup() {
+        let original = GStr::new("persistido-en-json");
+        let json = serde_json::to_string(&original).unwrap();
+        assert_eq!(json, "\"persistido-en-json\"");
+
+        let reconstruido: GStr = serde_json::from_str(&json).unwrap();
+        // debe re-internar contra la tabla existente, no crear un nodo aparte
+        assert_eq!(original == reconstruido, true);
+    }
+
+    #[test]
+    fn interner_aisla_sus_propias_cadenas() {
+        let global = GStr::new("aislado");
+
+        {
+            let propio = Interner::new();
+            let local_uno = propio.intern("aislado");
+            let local_dos = propio.intern("aislado");
+            // misma tabla propia: se dedup entre si, como en la global
+            assert_eq!(local_uno == local_dos, true);
+            // pero son un nodo distinto al de la tabla global
+            assert_eq!(local_uno == global, false);
+            assert_eq!(local_uno.as_ref(), global.as_ref());
+        } // `propio` se dropea aqui, liberando sus propios nodos
+
+        // la cadena de la tabla global sigue viva y utilizable
+        assert_eq!(global.as_ref(), "aislado");
+    }
+
+    #[test]
+    fn gstr_sobrevive_al_drop_de_su_interner() {
+        // `GStr` no tiene un lifetime atado a su `Interner`, asi que nada
+        // impide en codigo seguro que sobreviva al `Interner` del que salio.
+        // El `Interner` debe preferir perder la arena antes que liberarla
+        // con un `GStr` todavia vivo apuntando a ella.
+        let superviviente = {
+            let propio = Interner::new();
+            propio.intern("sobrevive-al-interner")
+        }; // `propio` se dropea aqui, pero `superviviente` sigue vivo
+
+        // la cadena debe seguir siendo legible sin usar memoria liberada
+        assert_eq!(superviviente.as_ref(), "sobrevive-al-interner");
+
+        // y dropearla no debe hacer doble-free del nodo que el `Interner`
+        // dejo sin liberar
+        drop(superviviente);
+    }
+
+    #[test]
+    fn eq_hash_ord_consistentes_entre_interners() {
+        use std::collections::{BTreeSet, HashSet};
+
+        let a = GStr::new("mismo-texto-orden");
+        let b = GStr::new("mismo-texto-orden");
+        // misma tabla (la global): mismo nodo, deben ser iguales en todo
+        assert_eq!(a == b, true);
+        assert_eq!(a.cmp(&b) == std::cmp::Ordering::Equal, true);
+
+        let otro_interner = Interner::new();
+        let c = otro_interner.intern("mismo-texto-orden");
+        // interner distinto: nodo distinto, Eq/Hash/Ord deben ser coherentes
+        // entre si (ninguno puede tratar a `a` y `c` como "iguales" si los
+        // otros no lo hacen)
+        assert_eq!(a == c, false);
+        assert_eq!(a.cmp(&c) == std::cmp::Ordering::Equal, false);
+
+        let mut conjunto_hash = HashSet::new();
+        conjunto_hash.insert(a.clone());
+        conjunto_hash.insert(c.clone());
+        assert_eq!(conjunto_hash.len(), 2);
+
+        let mut conjunto_btree = BTreeSet::new();
+        conjunto_btree.insert(a);
+        conjunto_btree.insert(c);
+        assert_eq!(conjunto_btree.len(), 2);
+    }
+
+    #[test]
+    fn ord_ordena_por_contenido_no_por_direccion_de_memoria() {
+        // el caso comun (un solo interner, o interners distintos con
+        // contenido distinto) debe ordenar alfabeticamente, no por la
+        // direccion del nodo interno
+        let z = GStr::new("zeta");
+        let a = GStr::new("alfa");
+        let m = GStr::new("eme");
+        let mut cadenas = vec![z.clone(), a.clone(), m.clone()];
+        cadenas.sort();
+        assert_eq!(
+            cadenas.iter().map(|g| g.as_ref()).collect::<Vec<_>>(),
+            vec!["alfa", "eme", "zeta"]
+        );
+    }
+
+    #[test]
+    fn indice_por_hash_reencuentra_cadenas_ya_internadas() {
+        let mut vistos = Vec::new();
+        for i in 0..50 {
+            vistos.push(GStr::new(format!("bucket{i}")));
+        }
+        // al volver a internar las mismas cadenas, la busqueda por bucket
+        // debe devolver el mismo nodo en vez de crear uno nuevo
+        for (i, original) in vistos.iter().enumerate() {
+            let de_nuevo = GStr::new(format!("bucket{i}"));
+            assert_eq!(*original == de_nuevo, true);
+        }
+    }
+
+    #[test]
+    fn try_new_coincide_con_new_en_el_camino_feliz() {
+        // en ausencia de envenenamiento o fallo de asignacion, try_new/try_intern
+        // deben devolver Ok y dedup igual que sus contrapartes que hacen panic
+        let via_try = GStr::try_new("sin-panico").expect("no deberia fallar aqui");
+        let via_new = GStr::new("sin-panico");
+        assert_eq!(via_try == via_new, true);
+
+        let propio = Interner::new();
+        let a = propio.try_intern("arena-propia").expect("no deberia fallar aqui");
+        let b = propio.intern("arena-propia");
+        assert_eq!(a == b, true);
+    }
+
+    #[test]
+    fn huella_de_128_bits_distingue_cadenas_parecidas_y_dedup_las_iguales() {
+        // cadenas de contenido muy similar no deben colisionar en un mismo nodo
+        let a = GStr::new("clave-001");
+        let b = GStr::new("clave-002");
+        assert_eq!(a == b, false);
+
+        // pero la misma cadena si debe reencontrar el nodo ya creado
+        let a_de_nuevo = GStr::new("clave-001");
+        assert_eq!(a == a_de_nuevo, true);
     }
 }
\ No newline at end of file
@@ -0,0 +1,31 @@
+//! Implementaciones de `serde::Serialize`/`Deserialize` para `GStr`,
+//! disponibles bajo la feature `serde`.
+//!
+//! Al serializar solo se emite la cadena (`&str`) que envuelve el `GStr`.
+//! Al deserializar no se reconstruye un `GStrInterner` a mano: se deserializa
+//! a un `String` normal y se pasa por [`GStr::try_new`], para que la cadena
+//! quede deduplicada contra la tabla existente y comparta el contador de
+//! referencias con cualquier otra copia ya interna, sin hacer panic si el
+//! interner global no puede internarla (mutex envenenado o sin memoria).
+use crate::GStr;
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+
+impl Serialize for GStr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_ref())
+    }
+}
+
+impl<'de> Deserialize<'de> for GStr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let valor = String::deserialize(deserializer)?;
+        GStr::try_new(valor).map_err(D::Error::custom)
+    }
+}